@@ -1,7 +1,9 @@
 //! This reverts the xoring done by the reMarkable.
 //! It is supposed to be compiled for the host architecture to run on the PC.
 
-use restream::xor::UnxorStream; // restream her refers to the lib part. Possible includes are in lib.rs.
+use restream::codec::Codec; // restream her refers to the lib part. Possible includes are in lib.rs.
+use restream::rle::UnRleStream;
+use restream::xor::UnxorStream;
 use std::io::{stdin, stdout, Read, Result, Write};
 
 use clap::{crate_authors, crate_version, Clap};
@@ -11,6 +13,19 @@ use clap::{crate_authors, crate_version, Clap};
 pub struct Opts {
     #[clap(about = "Block size used for unxoring. Should be same as framebuffer size.")]
     block_size: usize,
+
+    #[clap(
+        long,
+        default_value = "lz4",
+        about = "Wire codec the stream was compressed with. Must match what the device streamed with: lz4, zstd or none."
+    )]
+    codec: Codec,
+
+    #[clap(
+        long,
+        about = "Must be set if the device streamed with --delta-rle, so the zero-run tokens get expanded before unxoring."
+    )]
+    delta_rle: bool,
 }
 
 fn main() -> Result<()> {
@@ -18,7 +33,13 @@ fn main() -> Result<()> {
 
     let stdin = stdin();
     let stdout = stdout();
-    let mut stdin_wrapper = UnxorStream::new(opts.block_size, stdin.lock());
+    let decompressed = opts.codec.decompress(stdin.lock());
+    let unrled: Box<dyn Read> = if opts.delta_rle {
+        Box::new(UnRleStream::new(opts.block_size, decompressed))
+    } else {
+        decompressed
+    };
+    let mut stdin_wrapper = UnxorStream::new(opts.block_size, unrled);
     let mut stdout = stdout.lock();
 
     let mut buf = [0u8; 1024 * 1024 * 4];
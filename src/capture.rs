@@ -0,0 +1,151 @@
+//! Single shared capture loop, fanned out to any number of subscribers.
+//!
+//! Previously every consumer (the `--connect` socket, and now every
+//! `--serve` WebSocket) drove its own `ReStreamer`, each re-reading
+//! `/proc/<pid>/mem` independently. Instead, [`FrameCapture`] reads each
+//! framebuffer frame exactly once on a dedicated thread and publishes it to
+//! a `tokio::sync::broadcast` channel; a slow subscriber just misses frames
+//! instead of stalling the capture loop for everyone else.
+
+use crate::{build_pipeline, CaptureConfig};
+use anyhow::Result;
+use std::io::Read;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// How many frames the broadcast channel keeps around. A subscriber that
+/// falls behind by more than this many frames gets `RecvError::Lagged` and
+/// jumps straight to the newest frame instead of catching up frame by frame.
+const CHANNEL_CAPACITY: usize = 4;
+
+pub struct FrameCapture {
+    frame_size: usize,
+    dimensions: FrameDimensions,
+    sender: broadcast::Sender<Arc<Vec<u8>>>,
+}
+
+/// The pixel geometry of a captured frame, kept around so consumers that
+/// never see the original `CaptureConfig` (e.g. `--serve` clients) can still
+/// learn how to interpret the raw bytes.
+#[derive(Clone, Copy)]
+pub struct FrameDimensions {
+    pub width: usize,
+    pub height: usize,
+    pub bytes_per_pixel: usize,
+    pub monow: bool,
+}
+
+impl FrameCapture {
+    /// Spawns the capture loop on its own thread and returns a handle that
+    /// can be subscribed to from as many async tasks as needed.
+    pub fn spawn(config: CaptureConfig) -> Result<Self> {
+        let frame_size = frame_size(&config);
+        let dimensions = FrameDimensions {
+            width: config.width,
+            height: config.height,
+            bytes_per_pixel: config.bytes_per_pixel,
+            monow: config.monow,
+        };
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let loop_sender = sender.clone();
+
+        std::thread::spawn(move || {
+            if let Err(e) = capture_loop(config, frame_size, loop_sender) {
+                eprintln!("Capture loop stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            frame_size,
+            dimensions,
+            sender,
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Vec<u8>>> {
+        self.sender.subscribe()
+    }
+
+    /// Size in bytes of a single captured frame (after monow transcoding, if
+    /// enabled). `XorStream`/`RleStream` need this to stay aligned with
+    /// frame boundaries, so it's exposed rather than recomputed.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Pixel geometry of a captured frame, for consumers (e.g. `--serve`
+    /// clients) that need to know how to lay the raw bytes back out.
+    pub fn dimensions(&self) -> FrameDimensions {
+        self.dimensions
+    }
+}
+
+fn frame_size(config: &CaptureConfig) -> usize {
+    if config.monow {
+        (config.width * config.height) / 8
+    } else {
+        config.width * config.height * config.bytes_per_pixel
+    }
+}
+
+fn capture_loop(config: CaptureConfig, frame_size: usize, sender: broadcast::Sender<Arc<Vec<u8>>>) -> Result<()> {
+    let mut pipeline = build_pipeline(&config)?;
+    loop {
+        let mut frame = vec![0u8; frame_size];
+        pipeline.read_exact(&mut frame)?;
+        // Sending fails only when there are no subscribers left (e.g. no
+        // viewer has connected yet); that's not an error, just keep capturing.
+        let _ = sender.send(Arc::new(frame));
+    }
+}
+
+/// Bridges a `broadcast::Receiver` back into a blocking `Read`, one frame at
+/// a time, so the existing synchronous compressors can consume it unchanged.
+pub struct BroadcastReader {
+    handle: tokio::runtime::Handle,
+    receiver: broadcast::Receiver<Arc<Vec<u8>>>,
+    current: Option<(Arc<Vec<u8>>, usize)>,
+}
+
+impl BroadcastReader {
+    pub fn new(handle: tokio::runtime::Handle, receiver: broadcast::Receiver<Arc<Vec<u8>>>) -> Self {
+        Self {
+            handle,
+            receiver,
+            current: None,
+        }
+    }
+
+    fn next_frame(&mut self) -> std::io::Result<Arc<Vec<u8>>> {
+        loop {
+            match self.handle.block_on(self.receiver.recv()) {
+                Ok(frame) => return Ok(frame),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+                }
+            }
+        }
+    }
+}
+
+impl Read for BroadcastReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.current.is_none() {
+            let frame = self.next_frame()?;
+            self.current = Some((frame, 0));
+        }
+
+        let (frame, pos) = self.current.as_mut().expect("just filled above");
+        let available = &frame[*pos..];
+        let to_copy = available.len().min(buf.len());
+        buf[..to_copy].copy_from_slice(&available[..to_copy]);
+        *pos += to_copy;
+
+        if *pos == frame.len() {
+            self.current = None;
+        }
+
+        Ok(to_copy)
+    }
+}
@@ -0,0 +1,6 @@
+//! Library half of the crate: the pieces `src/bin/unxor.rs` (run on the
+//! host) needs to mirror what `src/main.rs` (run on the reMarkable) does.
+
+pub mod codec;
+pub mod rle;
+pub mod xor;
@@ -2,14 +2,20 @@
 extern crate anyhow;
 extern crate lz_fear;
 
+mod capture;
+mod server;
+
 use anyhow::{Context, Result};
+use capture::{BroadcastReader, FrameCapture};
 use clap::{crate_authors, crate_version, Clap};
-use lz_fear::CompressionSettings;
+use restream::codec::Codec;
+use restream::rle::RleStream;
+use restream::xor::XorStream;
 
-use std::default::Default;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::process::Command;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 #[derive(Clap)]
@@ -36,74 +42,143 @@ pub struct Opts {
         about = "Always transcode the framebuffer to monow (instead streaming the native pix_fmt)"
     )]
     monow: bool,
+
+    #[clap(
+        long,
+        default_value = "lz4",
+        about = "Wire codec used to compress the stream. One of: lz4, zstd, none. Must match the codec the host decodes with."
+    )]
+    codec: Codec,
+
+    #[clap(
+        long,
+        name = "bind-addr",
+        about = "Instead of streaming to a single --connect target or stdout, serve the framebuffer over HTTP/WebSocket on the given address (e.g. 0.0.0.0:8080) so any browser on the LAN can watch it."
+    )]
+    serve: Option<String>,
+
+    #[clap(
+        long,
+        about = "Run-length-encode the zero-heavy xor-diff stream before compression. Helps most on mostly-static e-ink screens; the host must be told to decode with the same flag."
+    )]
+    delta_rle: bool,
 }
 
-fn main() -> Result<()> {
-    let ref opts: Opts = Opts::parse();
+/// Everything needed to start a fresh capture pipeline: which device file to
+/// read from, where the framebuffer starts in it and how to interpret it.
+/// Kept separate from `Opts` so `--serve` can spin up a brand new pipeline
+/// per WebSocket connection instead of reusing a single one.
+#[derive(Clone)]
+pub struct CaptureConfig {
+    pub fb_path: String,
+    pub offset: usize,
+    pub width: usize,
+    pub height: usize,
+    pub bytes_per_pixel: usize,
+    pub fps_cap: Option<f32>,
+    pub monow: bool,
+}
 
+fn resolve_capture_config(opts: &Opts) -> Result<CaptureConfig> {
     let version = remarkable_version()?;
-    let streamer: Box<dyn Read> = if version == "reMarkable 1.0\n" {
-        let width = 1408;
-        let height = 1872;
-        let bytes_per_pixel = 2;
-
-        let restreamer =
-            ReStreamer::init("/dev/fb0", 0, width, height, bytes_per_pixel, opts.fps_cap)?;
-        if opts.monow {
-            Box::new(MonowTranscoder::new(
-                width,
-                height,
-                bytes_per_pixel,
-                restreamer,
-            )?)
-        } else {
-            Box::new(restreamer)
-        }
+    if version == "reMarkable 1.0\n" {
+        Ok(CaptureConfig {
+            fb_path: "/dev/fb0".to_string(),
+            offset: 0,
+            width: 1408,
+            height: 1872,
+            bytes_per_pixel: 2,
+            fps_cap: opts.fps_cap,
+            monow: opts.monow,
+        })
     } else if version == "reMarkable 2.0\n" {
-        let width = 1404;
-        let height = 1872;
-        let bytes_per_pixel = 1;
-
         let pid = xochitl_pid()?;
         let offset = rm2_fb_offset(pid)?;
-        let mem = format!("/proc/{}/mem", pid);
-
-        let restreamer =
-            ReStreamer::init(&mem, offset, width, height, bytes_per_pixel, opts.fps_cap)?;
-        if opts.monow {
-            Box::new(MonowTranscoder::new(
-                width,
-                height,
-                bytes_per_pixel,
-                restreamer,
-            )?)
-        } else {
-            Box::new(restreamer)
-        }
+        Ok(CaptureConfig {
+            fb_path: format!("/proc/{}/mem", pid),
+            offset,
+            width: 1404,
+            height: 1872,
+            bytes_per_pixel: 1,
+            fps_cap: opts.fps_cap,
+            monow: opts.monow,
+        })
     } else {
         Err(anyhow!(
             "Unknown reMarkable version: {}\nPlease open a feature request to support your device.",
             version
-        ))?
-    };
+        ))
+    }
+}
+
+/// Builds a fresh capture pipeline (`ReStreamer`, optionally wrapped in
+/// `MonowTranscoder`) from a `CaptureConfig`. Each call opens its own file
+/// handle, so this can be called once per viewer.
+pub fn build_pipeline(config: &CaptureConfig) -> Result<Box<dyn Read + Send>> {
+    let restreamer = ReStreamer::init(
+        &config.fb_path,
+        config.offset,
+        config.width,
+        config.height,
+        config.bytes_per_pixel,
+        config.fps_cap,
+    )?;
+
+    if config.monow {
+        Ok(Box::new(MonowTranscoder::new(
+            config.width,
+            config.height,
+            config.bytes_per_pixel,
+            restreamer,
+        )?))
+    } else {
+        Ok(Box::new(restreamer))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let ref opts: Opts = Opts::parse();
 
-    let stdout = std::io::stdout();
-    let data_target: Box<dyn Write> = if let Some(ref address) = opts.connect {
+    let capture_config = resolve_capture_config(opts)?;
+
+    // The default block size will make the monow transcoding seem extremly
+    // laggy since the frames a lot smaller and better compressable.
+    let block_size = if opts.monow { 64 * 1024 } else { 4 * 1024 * 1024 };
+
+    let capture = Arc::new(FrameCapture::spawn(capture_config)?);
+
+    if let Some(bind_addr) = &opts.serve {
+        return server::run(bind_addr.clone(), capture, opts.codec, block_size, opts.delta_rle).await;
+    }
+
+    let data_target: Box<dyn Write + Send> = if let Some(ref address) = opts.connect {
         let conn = std::net::TcpStream::connect(address)?;
         conn.set_write_timeout(Some(std::time::Duration::from_secs(3)))?;
         Box::new(conn)
     } else {
-        Box::new(stdout.lock())
+        Box::new(std::io::stdout())
     };
 
-    let mut lz4: CompressionSettings = CompressionSettings::default();
-    if opts.monow {
-        // The default block size will make the monow transcoding seem extremly
-        // laggy since the frames a lot smaller and better compressable.
-        lz4.block_size(64 * 1024);
-    }
-    lz4.compress(streamer, data_target)
-        .context("Error while compressing framebuffer stream")
+    let receiver = capture.subscribe();
+    let handle = tokio::runtime::Handle::current();
+    let frame_size = capture.frame_size();
+    let codec = opts.codec;
+    let delta_rle = opts.delta_rle;
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let reader = BroadcastReader::new(handle, receiver);
+        let xor_reader = XorStream::new(frame_size, reader);
+        if delta_rle {
+            let rle_reader = RleStream::new(frame_size, xor_reader);
+            codec.compress(block_size, rle_reader, data_target)
+        } else {
+            codec.compress(block_size, xor_reader, data_target)
+        }
+    })
+    .await
+    .context("Compression task panicked")??;
+
+    Ok(())
 }
 
 fn remarkable_version() -> Result<String> {
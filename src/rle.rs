@@ -0,0 +1,232 @@
+use std::io::{Error, ErrorKind, Read, Result};
+
+/// Run-length-encodes the zero-heavy output of `XorStream` before it hits
+/// the compressor: on an e-ink screen that barely changes, a frame is
+/// mostly long runs of zero bytes, which this turns into a handful of
+/// tokens instead of handing them to the compressor byte-for-byte.
+///
+/// Each frame (`block_size` bytes, matching the actual framebuffer/monow
+/// frame size) is encoded independently as alternating
+/// `(zero_run, literal_run, literal_bytes...)` tokens, both run lengths
+/// written as LEB128 varints. Encoding frame-by-frame (rather than letting
+/// a run span two frames) keeps this aligned with `XorStream`'s own
+/// per-frame cache on the other end.
+pub struct RleStream<R> {
+    inner: R,
+    frame: Vec<u8>,
+    encoded: std::io::Cursor<Vec<u8>>,
+}
+
+impl<R> RleStream<R> {
+    pub fn new(block_size: usize, inner: R) -> Self {
+        Self {
+            inner,
+            frame: vec![0u8; block_size],
+            encoded: std::io::Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl<R: Read> RleStream<R> {
+    /// Returns `Ok(false)` once the inner stream has cleanly ended on a frame
+    /// boundary (no more frames to encode), or an error if it ended partway
+    /// through one.
+    fn refill(&mut self) -> Result<bool> {
+        let filled = read_block(&mut self.inner, &mut self.frame)?;
+        if filled == 0 {
+            return Ok(false);
+        }
+        if filled < self.frame.len() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "stream ended partway through a frame",
+            ));
+        }
+
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < self.frame.len() {
+            let zero_start = i;
+            while i < self.frame.len() && self.frame[i] == 0 {
+                i += 1;
+            }
+            let zero_run = i - zero_start;
+
+            let literal_start = i;
+            while i < self.frame.len() && self.frame[i] != 0 {
+                i += 1;
+            }
+            let literal_run = i - literal_start;
+
+            write_varint(&mut out, zero_run as u64);
+            write_varint(&mut out, literal_run as u64);
+            out.extend_from_slice(&self.frame[literal_start..literal_start + literal_run]);
+        }
+
+        self.encoded = std::io::Cursor::new(out);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for RleStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let read = self.encoded.read(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            if !self.refill()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Reverses [`RleStream`], reconstructing the exact xor-diff byte stream
+/// `UnxorStream` expects, one frame (`block_size` bytes) at a time.
+pub struct UnRleStream<R> {
+    inner: R,
+    frame_size: usize,
+    decoded: std::io::Cursor<Vec<u8>>,
+}
+
+impl<R> UnRleStream<R> {
+    pub fn new(block_size: usize, inner: R) -> Self {
+        Self {
+            inner,
+            frame_size: block_size,
+            decoded: std::io::Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl<R: Read> UnRleStream<R> {
+    /// Returns `Ok(false)` once the inner stream has cleanly ended on a frame
+    /// boundary (no more frames to decode), or an error if it ended partway
+    /// through one.
+    fn refill(&mut self) -> Result<bool> {
+        let mut frame = Vec::with_capacity(self.frame_size);
+        let zero_run = match read_varint_at_frame_start(&mut self.inner)? {
+            Some(v) => v as usize,
+            None => return Ok(false),
+        };
+        frame.resize(frame.len() + zero_run, 0);
+
+        loop {
+            let literal_run = read_varint(&mut self.inner)? as usize;
+            let mut literal = vec![0u8; literal_run];
+            self.inner.read_exact(&mut literal)?;
+            frame.extend_from_slice(&literal);
+
+            if frame.len() >= self.frame_size {
+                break;
+            }
+
+            let zero_run = read_varint(&mut self.inner)? as usize;
+            frame.resize(frame.len() + zero_run, 0);
+        }
+
+        self.decoded = std::io::Cursor::new(frame);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for UnRleStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let read = self.decoded.read(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            if !self.refill()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Fills `block` from `reader`, stopping early (and returning the short
+/// count) on EOF instead of erroring, so callers can tell a clean end of
+/// stream apart from a frame truncated partway through.
+fn read_block(reader: &mut impl Read, block: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < block.len() {
+        let read = reader.read(&mut block[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+/// Like [`read_varint`], but returns `Ok(None)` instead of erroring if
+/// `reader` is cleanly at EOF before the varint's first byte (i.e. right on
+/// a frame boundary). Any EOF after that first byte is still a truncation
+/// error, same as `read_varint`.
+fn read_varint_at_frame_start(reader: &mut impl Read) -> Result<Option<u64>> {
+    let mut byte = [0u8; 1];
+    if read_block(reader, &mut byte)? == 0 {
+        return Ok(None);
+    }
+    let mut value = (byte[0] & 0x7f) as u64;
+    let mut shift = 7;
+    while byte[0] & 0x80 != 0 {
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+    Ok(Some(value))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trips_a_finite_frame_aligned_stream_and_signals_clean_eof() {
+        let frame_size = 16;
+        let frames = [
+            vec![0u8; frame_size],
+            (0..frame_size as u8).collect::<Vec<_>>(),
+        ];
+        let input: Vec<u8> = frames.concat();
+
+        let mut encoder = RleStream::new(frame_size, Cursor::new(input.clone()));
+        let mut encoded = Vec::new();
+        encoder.read_to_end(&mut encoded).expect("clean EOF, not an error");
+
+        let mut decoder = UnRleStream::new(frame_size, Cursor::new(encoded));
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded).expect("clean EOF, not an error");
+
+        assert_eq!(decoded, input);
+    }
+}
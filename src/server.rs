@@ -0,0 +1,103 @@
+//! `--serve` mode: an embedded HTTP/WebSocket server so a browser on the LAN
+//! can watch the tablet's screen without anyone first setting up an external
+//! listener for `--connect` to talk to.
+//!
+//! Every accepted WebSocket upgrade subscribes to the single shared
+//! [`FrameCapture`] and gets its own `XorStream` + codec pipeline, but none
+//! of them re-read the framebuffer themselves.
+
+use crate::capture::{BroadcastReader, FrameCapture};
+use anyhow::{Context, Result};
+use restream::codec::Codec;
+use restream::rle::RleStream;
+use restream::xor::XorStream;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::Arc;
+use warp::ws::{Message, WebSocket};
+use warp::Filter;
+
+const INDEX_HTML: &str = include_str!("server/index.html");
+
+/// Runs the embedded server until it is shut down (i.e. forever, under
+/// normal operation). Expected to be awaited from inside the shared tokio
+/// runtime `main` already runs in.
+pub async fn run(
+    bind_addr: String,
+    capture: Arc<FrameCapture>,
+    codec: Codec,
+    block_size: usize,
+    delta_rle: bool,
+) -> Result<()> {
+    let addr: std::net::SocketAddr = bind_addr
+        .parse()
+        .with_context(|| format!("Invalid --serve address '{}'", bind_addr))?;
+
+    let index = warp::path::end().map(|| warp::reply::html(INDEX_HTML));
+
+    let ws_route = warp::path("ws").and(warp::ws()).map(move |ws: warp::ws::Ws| {
+        let capture = capture.clone();
+        ws.on_upgrade(move |socket| handle_connection(socket, capture, codec, block_size, delta_rle))
+    });
+
+    eprintln!("Serving reStream on http://{}", addr);
+    warp::serve(index.or(ws_route)).run(addr).await;
+    Ok(())
+}
+
+async fn handle_connection(
+    ws: WebSocket,
+    capture: Arc<FrameCapture>,
+    codec: Codec,
+    block_size: usize,
+    delta_rle: bool,
+) {
+    let (mut ws_tx, _ws_rx) = ws.split();
+
+    let dimensions = capture.dimensions();
+    let dimensions_msg = format!(
+        "{{\"width\":{},\"height\":{},\"bytes_per_pixel\":{},\"monow\":{}}}",
+        dimensions.width, dimensions.height, dimensions.bytes_per_pixel, dimensions.monow
+    );
+    if ws_tx.send(Message::text(dimensions_msg)).await.is_err() {
+        return;
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(8);
+
+    let receiver = capture.subscribe();
+    let handle = tokio::runtime::Handle::current();
+    let frame_size = capture.frame_size();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let reader = BroadcastReader::new(handle, receiver);
+        let xor_reader = XorStream::new(frame_size, reader);
+        if delta_rle {
+            let rle_reader = RleStream::new(frame_size, xor_reader);
+            codec.compress(block_size, rle_reader, ChannelWriter(tx))
+        } else {
+            codec.compress(block_size, xor_reader, ChannelWriter(tx))
+        }
+    });
+
+    while let Some(chunk) = rx.recv().await {
+        if ws_tx.send(Message::binary(chunk)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Adapts a tokio mpsc sender to `std::io::Write` so the (blocking) codec
+/// compressors can write straight into the async WebSocket forwarding task.
+struct ChannelWriter(tokio::sync::mpsc::Sender<Vec<u8>>);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .blocking_send(buf.to_vec())
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
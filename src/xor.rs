@@ -25,10 +25,10 @@ impl<R> XorStream<R> {
 impl<R: Read> Read for XorStream<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let bytes_read = self.inner.read(buf)?;
-        for i in 0..bytes_read {
+        for byte in &mut buf[..bytes_read] {
             let prev_byte = self.last_cache[self.last_cache_index];
-            self.last_cache[self.last_cache_index] = buf[i];
-            buf[i] ^= prev_byte;
+            self.last_cache[self.last_cache_index] = *byte;
+            *byte ^= prev_byte;
 
             self.last_cache_index = (self.last_cache_index + 1) % self.last_cache.len();
         }
@@ -59,9 +59,9 @@ impl<R> UnxorStream<R> {
 impl<R: Read> Read for UnxorStream<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let bytes_read = self.inner.read(buf)?;
-        for i in 0..bytes_read {
-            buf[i] = (self.diff_cache.pop_front().unwrap()) ^ buf[i];
-            self.diff_cache.push_back(buf[i]);
+        for byte in &mut buf[..bytes_read] {
+            *byte ^= self.diff_cache.pop_front().unwrap();
+            self.diff_cache.push_back(*byte);
         }
         Ok(bytes_read)
     }